@@ -1,22 +1,116 @@
+mod pvc;
+
 use std::io::Write;
+use std::time::Duration;
 use tracing::*;
 
-use futures::{join, stream, AsyncBufReadExt, StreamExt, TryStreamExt};
-use k8s_openapi::api::core::v1::Pod;
+use clap::Parser;
+use futures::{join, stream, AsyncBufReadExt, FutureExt, StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::{PersistentVolumeClaim, Pod};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Status;
 
 use kube::{
-    api::{
-        Api, AttachParams, AttachedProcess, DeleteParams, LogParams, PostParams, ResourceExt,
-        WatchEvent, WatchParams,
-    },
+    api::{Api, AttachParams, AttachedProcess, DeleteParams, LogParams, PostParams, ResourceExt},
+    runtime::wait::{await_condition, conditions},
     Client,
 };
+use tokio_util::sync::CancellationToken;
+
+use pvc::PvcSpec;
+
+/// CLI configuration for the hello-kubers example.
+///
+/// Each phase of the pod lifecycle (readiness, attach/exec, log streaming) gets its own
+/// timeout budget so a slow image pull can be told apart from a stalled stream.
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Config {
+    /// Timeout for attaching to the pod and for streaming its attached output.
+    #[arg(long, env = "KUBERS_TIMEOUT", default_value = "30s")]
+    timeout: humantime::Duration,
+
+    /// Timeout for the pod to become ready (image pull + scheduling + readiness watch).
+    #[arg(long, env = "KUBERS_SETUP_TIMEOUT", default_value = "60s")]
+    setup_timeout: humantime::Duration,
+
+    /// Timeout for the log-follow phase once the pod is running.
+    #[arg(long, env = "KUBERS_TRANSFER_TIMEOUT", default_value = "30s")]
+    transfer_timeout: humantime::Duration,
+
+    /// Capacity, in bytes, of kube's stdout backpressure channel for `exec_capture` calls.
+    /// A slow reader stalls the attach pump at this many buffered bytes rather than
+    /// dropping output.
+    #[arg(long, env = "KUBERS_MAX_STDOUT_BUF_SIZE", default_value_t = 1024 * 1024)]
+    max_stdout_buf_size: usize,
+
+    /// Capacity, in bytes, of kube's stderr backpressure channel for `exec_capture` calls.
+    /// A slow reader stalls the attach pump at this many buffered bytes rather than
+    /// dropping output.
+    #[arg(long, env = "KUBERS_MAX_STDERR_BUF_SIZE", default_value_t = 1024 * 1024)]
+    max_stderr_buf_size: usize,
+
+    /// Namespace the pod and its PVC are created in. Defaults to the client's configured
+    /// namespace (`Api::default_namespaced`) when unset.
+    #[arg(long, env = "KUBERS_NAMESPACE")]
+    namespace: Option<String>,
+
+    /// Name of the PersistentVolumeClaim backing the pod's working directory.
+    #[arg(long, env = "KUBERS_PVC_NAME", default_value = "test-kane8n-data")]
+    pvc_name: String,
+
+    /// Requested storage size for the PVC, e.g. `1Gi`, if it needs to be created.
+    #[arg(long, env = "KUBERS_PVC_SIZE", default_value = "1Gi")]
+    pvc_size: String,
+
+    /// Storage class for the PVC, if it needs to be created. Defaults to the cluster default.
+    #[arg(long, env = "KUBERS_PVC_STORAGE_CLASS")]
+    pvc_storage_class: Option<String>,
+
+    /// Path inside the container where the PVC is mounted.
+    #[arg(long, env = "KUBERS_PVC_MOUNT_PATH", default_value = "/data")]
+    pvc_mount_path: String,
+
+    /// Keep the PVC around after teardown instead of deleting it with the pod.
+    #[arg(long, env = "KUBERS_RETAIN_PVC", default_value_t = false)]
+    retain_pvc: bool,
+}
+
+impl Config {
+    /// Builds the [`AttachParams`] used for [`exec_capture`] calls, with buffer sizes
+    /// capped at the configured `max_stdout_buf_size`/`max_stderr_buf_size`.
+    #[allow(dead_code)]
+    fn exec_attach_params(&self) -> AttachParams {
+        AttachParams::default()
+            .stdout(true)
+            .stderr(true)
+            .max_stdout_buf_size(self.max_stdout_buf_size)
+            .max_stderr_buf_size(self.max_stderr_buf_size)
+    }
+
+    /// Builds the [`PvcSpec`] describing the pod's working-directory volume claim.
+    fn pvc_spec(&self) -> PvcSpec {
+        PvcSpec {
+            name: self.pvc_name.clone(),
+            size: Quantity(self.pvc_size.clone()),
+            storage_class: self.pvc_storage_class.clone(),
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     tracing_subscriber::fmt::init();
+    let config = Config::parse();
     let client = Client::try_default().await?;
 
+    let pvcs: Api<PersistentVolumeClaim> = match &config.namespace {
+        Some(ns) => Api::namespaced(client.clone(), ns),
+        None => Api::default_namespaced(client.clone()),
+    };
+    let pvc_spec = config.pvc_spec();
+    pvc::setup_pvc(&pvcs, &pvc_spec).await?;
+
     info!("Creating a Pod that outputs \"Hello, kube-rs!\"");
     let p: Pod = serde_json::from_value(serde_json::json!({
         "apiVersion": "v1",
@@ -27,44 +121,117 @@ async fn main() -> anyhow::Result<()> {
                 "name": "test-kane8n",
                 "image": "alpine",
                 "command": ["sh", "-c", "echo \"Hello, kube-rs!\" && sleep 10"],
+                "volumeMounts": [{
+                    "name": "data",
+                    "mountPath": config.pvc_mount_path,
+                }],
+            }],
+            "volumes": [{
+                "name": "data",
+                "persistentVolumeClaim": { "claimName": pvc_spec.name },
             }],
         }
     }))?;
 
-    let pods: Api<Pod> = Api::default_namespaced(client);
+    let pods: Api<Pod> = match &config.namespace {
+        Some(ns) => Api::namespaced(client, ns),
+        None => Api::default_namespaced(client),
+    };
     // Stop on error including a pod already exists or is still being deleted.
     pods.create(&PostParams::default(), &p).await?;
 
     // Wait until the pod is running, otherwise we get 500 error.
-    let wp = WatchParams::default()
-        .fields("metadata.name=test-kane8n")
-        .timeout(10);
-    let mut stream = pods.watch(&wp, "0").await?.boxed();
-    while let Some(status) = stream.try_next().await? {
-        match status {
-            WatchEvent::Added(o) => {
-                info!("Added {}", o.name_any());
-            }
-            WatchEvent::Modified(o) => {
-                let s = o.status.as_ref().expect("status exists on pod");
-                if s.phase.clone().unwrap_or_default() == "Running" {
-                    info!("Ready to attach to {}", o.name_any());
-                    break;
-                }
-            }
-            _ => {}
-        }
+    let setup_timeout: Duration = config.setup_timeout.into();
+    if let Err(e) = wait_for_running(&pods, "test-kane8n", setup_timeout).await {
+        warn!("failed waiting for pod to become ready: {e}");
+        cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+        return Err(e);
     }
 
     let ap = AttachParams::default();
+    let attach_timeout: Duration = config.timeout.into();
     // Attach to see numbers printed on stdout.
-    let attached = pods.attach("test-kane8n", &ap).await?;
+    let mut attached = match tokio::time::timeout(attach_timeout, pods.attach("test-kane8n", &ap))
+        .await
+    {
+        Ok(res) => res?,
+        Err(e) => {
+            warn!("timed out attaching to pod: {e}");
+            cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+            return Err(e.into());
+        }
+    };
+
+    let cancel = CancellationToken::new();
     // Separate stdout/stderr outputs
-    // separate_outputs(attached).await;
-    // Combining stdout and stderr output.
-    combined_output(attached).await;
+    // separate_outputs(&mut attached, cancel.clone()).await;
+    // Combining stdout and stderr output, racing it against Ctrl-C so an interrupted run
+    // still tears down the pod instead of leaking it.
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            warn!("received Ctrl-C, aborting attach");
+            cancel.cancel();
+        }
+        res = tokio::time::timeout(attach_timeout, combined_output(&mut attached, cancel.clone())) => {
+            if res.is_err() {
+                warn!("timed out streaming attached output");
+                if let Some(status) = attached.take_status().and_then(|fut| fut.now_or_never().flatten()) {
+                    info!("{:?}", status);
+                }
+                cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+                anyhow::bail!("timed out streaming attached output");
+            }
+        }
+    }
+
+    if let Some(status_fut) = attached.take_status() {
+        if let Some(status) = status_fut.await {
+            info!("{:?}", status);
+        }
+    }
+
+    if cancel.is_cancelled() {
+        cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+        anyhow::bail!("cancelled by Ctrl-C");
+    }
 
     info!("Fetching logs for test-kane8n");
+    let transfer_timeout: Duration = config.transfer_timeout.into();
+    let log_cancel = CancellationToken::new();
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            warn!("received Ctrl-C, aborting log stream");
+            log_cancel.cancel();
+        }
+        res = tokio::time::timeout(transfer_timeout, follow_logs(&pods, log_cancel.clone())) => {
+            match res {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    warn!("error following logs: {e}");
+                    cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+                    return Err(e);
+                }
+                Err(e) => {
+                    warn!("timed out following logs: {e}");
+                    cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+                    return Err(e.into());
+                }
+            }
+        }
+    }
+
+    cleanup(&pods, &pvcs, &pvc_spec.name, config.retain_pvc).await;
+
+    if log_cancel.is_cancelled() {
+        anyhow::bail!("cancelled by Ctrl-C");
+    }
+
+    Ok(())
+}
+
+/// Streams logs for `test-kane8n` to stdout until the pod's log stream ends or `cancel`
+/// fires, at which point the loop returns early instead of running to completion.
+async fn follow_logs(pods: &Api<Pod>, cancel: CancellationToken) -> anyhow::Result<()> {
     let mut logs = pods
         .log_stream(
             "test-kane8n",
@@ -80,55 +247,242 @@ async fn main() -> anyhow::Result<()> {
         .await?
         .lines();
 
-    while let Some(line) = logs.try_next().await? {
-        println!("{}", line);
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            line = logs.try_next() => {
+                match line? {
+                    Some(line) => println!("{}", line),
+                    None => break,
+                }
+            }
+        }
     }
+    Ok(())
+}
 
-    // Delete it
-    pods.delete("test-kane8n", &DeleteParams::default())
-        .await?
-        .map_left(|pdel| {
-            assert_eq!(pdel.name_any(), "test-kane8n");
-        });
+/// Waits for `name` to reach the `Running` phase, bounded by `timeout`.
+///
+/// Built on `kube::runtime::wait::await_condition`, which watches the object until the
+/// condition holds rather than hand-inspecting `WatchEvent`s, so pod deletion and
+/// crash-looping containers are handled the same way upstream handles them.
+async fn wait_for_running(pods: &Api<Pod>, name: &str, timeout: Duration) -> anyhow::Result<()> {
+    tokio::time::timeout(
+        timeout,
+        await_condition(pods.clone(), name, conditions::is_pod_running()),
+    )
+    .await??;
+    info!("Ready to attach to {name}");
+    Ok(())
+}
 
+/// Waits for every container in `name` to report ready, bounded by `timeout`.
+///
+/// Use this instead of [`wait_for_running`] when the caller needs readiness probes to
+/// have passed, not just the pod phase to be `Running`.
+#[allow(dead_code)]
+async fn wait_for_container_ready(
+    pods: &Api<Pod>,
+    name: &str,
+    timeout: Duration,
+) -> anyhow::Result<()> {
+    tokio::time::timeout(
+        timeout,
+        await_condition(pods.clone(), name, is_container_ready()),
+    )
+    .await??;
+    info!("All containers ready on {name}");
     Ok(())
 }
 
+/// A [`kube::runtime::wait::Condition`] that holds once every container status reports
+/// `ready: true`, complementing `conditions::is_pod_running` which only checks the phase.
+fn is_container_ready() -> impl kube::runtime::wait::Condition<Pod> {
+    |obj: Option<&Pod>| {
+        obj.and_then(|pod| pod.status.as_ref())
+            .and_then(|status| status.container_statuses.as_ref())
+            .is_some_and(|statuses| !statuses.is_empty() && statuses.iter().all(|c| c.ready))
+    }
+}
+
+/// Best-effort pod teardown, used both on the happy path and when a phase times out.
+async fn cleanup(
+    pods: &Api<Pod>,
+    pvcs: &Api<PersistentVolumeClaim>,
+    pvc_name: &str,
+    retain_pvc: bool,
+) {
+    if let Err(e) = pods
+        .delete("test-kane8n", &DeleteParams::default())
+        .await
+        .map(|res| {
+            res.map_left(|pdel| {
+                assert_eq!(pdel.name_any(), "test-kane8n");
+            })
+        })
+    {
+        warn!("failed to clean up test-kane8n: {e}");
+    }
+
+    if retain_pvc {
+        info!("Retaining PersistentVolumeClaim {pvc_name}");
+    } else if let Err(e) = pvc::teardown_pvc(pvcs, pvc_name).await {
+        warn!("failed to clean up PersistentVolumeClaim {pvc_name}: {e}");
+    }
+}
+
+/// Outcome of [`exec_capture`]: the captured stdout/stderr plus the exec `Status`, if any.
+#[derive(Debug, Default)]
 #[allow(dead_code)]
-async fn separate_outputs(mut attached: AttachedProcess) {
+struct ExecOutput {
+    stdout: Option<String>,
+    stderr: Option<String>,
+    status: Option<Status>,
+}
+
+impl ExecOutput {
+    /// The process exit code, parsed out of the exec `Status`'s details, if it reported one.
+    #[allow(dead_code)]
+    fn exit_code(&self) -> Option<i32> {
+        self.status
+            .as_ref()?
+            .details
+            .as_ref()?
+            .causes
+            .as_ref()?
+            .iter()
+            .find(|cause| cause.reason.as_deref() == Some("ExitCode"))
+            .and_then(|cause| cause.message.as_ref())
+            .and_then(|message| message.parse().ok())
+    }
+}
+
+/// Runs `command` in `name` via `pods.exec`, draining stdout/stderr into owned `String`s
+/// and the final `Status` into [`ExecOutput`] instead of writing straight to process stdio.
+///
+/// `ap`'s `max_stdout_buf_size`/`max_stderr_buf_size` size kube's per-stream backpressure
+/// channel, not a cap on captured output: stdout and stderr are drained concurrently here
+/// so neither channel can fill and stall the other, but a sufficiently chatty command can
+/// still grow `ExecOutput` without bound.
+#[allow(dead_code)]
+async fn exec_capture(
+    pods: &Api<Pod>,
+    name: &str,
+    command: Vec<&str>,
+    ap: AttachParams,
+) -> anyhow::Result<ExecOutput> {
+    let mut attached = pods.exec(name, command, &ap).await?;
+    let stdout = attached.stdout();
+    let stderr = attached.stderr();
+
+    let stdout_fut = async {
+        match stdout {
+            Some(stdout) => {
+                tokio_util::io::ReaderStream::new(stdout)
+                    .try_fold(Vec::new(), |mut acc, bytes| async {
+                        acc.extend_from_slice(&bytes);
+                        Ok(acc)
+                    })
+                    .await
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    };
+    let stderr_fut = async {
+        match stderr {
+            Some(stderr) => {
+                tokio_util::io::ReaderStream::new(stderr)
+                    .try_fold(Vec::new(), |mut acc, bytes| async {
+                        acc.extend_from_slice(&bytes);
+                        Ok(acc)
+                    })
+                    .await
+                    .map(Some)
+            }
+            None => Ok(None),
+        }
+    };
+    // Drained concurrently: kube's attach pump blocks once either bounded channel fills,
+    // so draining stdout to completion before starting stderr can deadlock on a command
+    // that writes more than `max_stderr_buf_size` to stderr before closing stdout.
+    let (stdout, stderr) = futures::try_join!(stdout_fut, stderr_fut)?;
+    let status = match attached.take_status() {
+        Some(status) => status.await,
+        None => None,
+    };
+
+    Ok(ExecOutput {
+        stdout: stdout.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+        stderr: stderr.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()),
+        status,
+    })
+}
+
+/// Writes `attached`'s stdout/stderr to the process's own stdout/stderr concurrently,
+/// returning early if `cancel` fires instead of running the streams to completion. Callers
+/// are responsible for draining `attached.take_status()` afterwards.
+#[allow(dead_code)]
+async fn separate_outputs(attached: &mut AttachedProcess, cancel: CancellationToken) {
     let stdout = tokio_util::io::ReaderStream::new(attached.stdout().unwrap());
-    let stdouts = stdout.for_each(|res| async {
-        if let Ok(bytes) = res {
-            let out = std::io::stdout();
-            out.lock().write_all(&bytes).unwrap();
+    let stdouts = async {
+        let mut stdout = stdout;
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                res = stdout.next() => {
+                    match res {
+                        Some(Ok(bytes)) => {
+                            let out = std::io::stdout();
+                            out.lock().write_all(&bytes).unwrap();
+                        }
+                        _ => break,
+                    }
+                }
+            }
         }
-    });
+    };
     let stderr = tokio_util::io::ReaderStream::new(attached.stderr().unwrap());
-    let stderrs = stderr.for_each(|res| async {
-        if let Ok(bytes) = res {
-            let out = std::io::stderr();
-            out.lock().write_all(&bytes).unwrap();
+    let stderrs = async {
+        let mut stderr = stderr;
+        loop {
+            tokio::select! {
+                _ = cancel.cancelled() => break,
+                res = stderr.next() => {
+                    match res {
+                        Some(Ok(bytes)) => {
+                            let out = std::io::stderr();
+                            out.lock().write_all(&bytes).unwrap();
+                        }
+                        _ => break,
+                    }
+                }
+            }
         }
-    });
+    };
 
     join!(stdouts, stderrs);
-    if let Some(status) = attached.take_status().unwrap().await {
-        info!("{:?}", status);
-    }
 }
 
-#[allow(dead_code)]
-async fn combined_output(mut attached: AttachedProcess) {
+/// Writes `attached`'s stdout and stderr to the process's own stdout on a single combined
+/// stream, returning early if `cancel` fires instead of running to completion. Callers are
+/// responsible for draining `attached.take_status()` afterwards.
+async fn combined_output(attached: &mut AttachedProcess, cancel: CancellationToken) {
     let stdout = tokio_util::io::ReaderStream::new(attached.stdout().unwrap());
     let stderr = tokio_util::io::ReaderStream::new(attached.stderr().unwrap());
-    let outputs = stream::select(stdout, stderr).for_each(|res| async {
-        if let Ok(bytes) = res {
-            let out = std::io::stdout();
-            out.lock().write_all(&bytes).unwrap();
+    let mut outputs = stream::select(stdout, stderr);
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            res = outputs.next() => {
+                match res {
+                    Some(Ok(bytes)) => {
+                        let out = std::io::stdout();
+                        out.lock().write_all(&bytes).unwrap();
+                    }
+                    _ => break,
+                }
+            }
         }
-    });
-    outputs.await;
-    if let Some(status) = attached.take_status().unwrap().await {
-        info!("{:?}", status);
     }
 }
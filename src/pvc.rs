@@ -0,0 +1,43 @@
+//! PVC-backed working directory for the example pod, so state survives pod restarts.
+
+use k8s_openapi::api::core::v1::PersistentVolumeClaim;
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::api::{Api, DeleteParams, PostParams};
+use tracing::*;
+
+/// Describes the PVC a pod's working directory should be backed by.
+#[derive(Debug, Clone)]
+pub struct PvcSpec {
+    pub name: String,
+    pub size: Quantity,
+    pub storage_class: Option<String>,
+}
+
+/// Ensures the PVC described by `spec` exists, creating a `ReadWriteOnce` volume claim of
+/// `spec.size` (with `spec.storage_class` if given) when it isn't already there.
+pub async fn setup_pvc(pvcs: &Api<PersistentVolumeClaim>, spec: &PvcSpec) -> anyhow::Result<()> {
+    if pvcs.get_opt(&spec.name).await?.is_some() {
+        info!("Reusing existing PersistentVolumeClaim {}", spec.name);
+        return Ok(());
+    }
+
+    info!("Creating PersistentVolumeClaim {}", spec.name);
+    let pvc: PersistentVolumeClaim = serde_json::from_value(serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "PersistentVolumeClaim",
+        "metadata": { "name": spec.name },
+        "spec": {
+            "accessModes": ["ReadWriteOnce"],
+            "resources": { "requests": { "storage": spec.size } },
+            "storageClassName": spec.storage_class,
+        }
+    }))?;
+    pvcs.create(&PostParams::default(), &pvc).await?;
+    Ok(())
+}
+
+/// Deletes the named PVC. Called during teardown unless the caller opted to retain it.
+pub async fn teardown_pvc(pvcs: &Api<PersistentVolumeClaim>, name: &str) -> anyhow::Result<()> {
+    pvcs.delete(name, &DeleteParams::default()).await?;
+    Ok(())
+}